@@ -0,0 +1,81 @@
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::io::Reader as ImageReader;
+use image::DynamicImage;
+use rxing::multi::{GenericMultipleBarcodeReader, MultipleBarcodeReader};
+use rxing::{
+    common::HybridBinarizer, BarcodeFormat, BinaryBitmap, BufferedImageLuminanceSource,
+    DecodeHintType, DecodeHintValue, DecodingHintDictionary, MultiFormatReader,
+};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+fn hints() -> DecodingHintDictionary {
+    let mut hints: DecodingHintDictionary = HashMap::new();
+    hints.insert(
+        DecodeHintType::POSSIBLE_FORMATS,
+        DecodeHintValue::PossibleFormats(HashSet::from([
+            BarcodeFormat::EAN_13,
+            BarcodeFormat::UPC_A,
+            BarcodeFormat::EAN_8,
+            BarcodeFormat::QR_CODE,
+        ])),
+    );
+    hints
+}
+
+/// Decodes every barcode present in `image`, supporting EAN-13, UPC-A,
+/// EAN-8 and QR in a single pass so a photo with several products returns
+/// one code per product instead of just the first match.
+fn decode_once(image: DynamicImage) -> Result<Vec<String>> {
+    let source = BufferedImageLuminanceSource::new(image);
+    let mut bitmap = BinaryBitmap::new(HybridBinarizer::new(source));
+
+    let mut reader = GenericMultipleBarcodeReader::new(MultiFormatReader::default());
+    let results = reader
+        .decode_multiple_with_hints(&mut bitmap, &hints())
+        .map_err(|e| anyhow::anyhow!("could not decode any barcode: {e}"))?;
+
+    Ok(results.into_iter().map(|r| r.getText().to_string()).collect())
+}
+
+/// Decodes `bytes` into one or more barcodes, retrying a fixed set of
+/// transforms when the raw image fails to decode. This is the main
+/// failure mode for phone photos where the barcode is sideways, tiny, or
+/// the lighting confuses the binarizer: grayscale, each 90-degree
+/// rotation, and a 2x upscale for low-resolution shots. The first variant
+/// that yields any codes wins; we only error once every variant has been
+/// tried.
+pub fn try_decode(bytes: &[u8]) -> Result<Vec<String>> {
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()?;
+
+    if let Ok(codes) = decode_once(image.clone()) {
+        if !codes.is_empty() {
+            return Ok(codes);
+        }
+    }
+
+    let transforms: Vec<Box<dyn Fn(&DynamicImage) -> DynamicImage>> = vec![
+        Box::new(|img: &DynamicImage| img.grayscale()),
+        Box::new(|img: &DynamicImage| img.rotate90()),
+        Box::new(|img: &DynamicImage| img.rotate180()),
+        Box::new(|img: &DynamicImage| img.rotate270()),
+        Box::new(|img: &DynamicImage| {
+            img.resize(img.width() * 2, img.height() * 2, FilterType::Lanczos3)
+        }),
+    ];
+
+    for transform in &transforms {
+        if let Ok(codes) = decode_once(transform(&image)) {
+            if !codes.is_empty() {
+                return Ok(codes);
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "could not decode any barcode after trying all transforms"
+    ))
+}
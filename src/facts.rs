@@ -0,0 +1,94 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Which field a `Nutrient` was sourced from, so the UI can badge the
+/// value accordingly instead of silently presenting a per-100g figure as
+/// if it were per serving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NutrientBasis {
+    PerServing,
+    Per100g,
+}
+
+impl NutrientBasis {
+    pub fn badge(self) -> &'static str {
+        match self {
+            NutrientBasis::PerServing => "por serving",
+            NutrientBasis::Per100g => "por 100g",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Nutrient {
+    pub value: f64,
+    pub basis: NutrientBasis,
+}
+
+/// A product's nutrition facts, parsed once out of the raw OpenFoodFacts
+/// response so the rest of the app never has to poke around in a
+/// `HashMap<String, Value>` again. Shared verbatim by the HTMX HTML path
+/// and the JSON API path.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductFacts {
+    pub product_name: Option<String>,
+    pub selected_image: Option<String>,
+    pub serving_size: Option<String>,
+    pub calories_kcal: Option<Nutrient>,
+    pub carbohydrates_g: Option<Nutrient>,
+    pub protein_g: Option<Nutrient>,
+    pub fat_g: Option<Nutrient>,
+    pub nutriscore_grade: Option<String>,
+    pub nova_group: Option<String>,
+    pub allergens: Vec<String>,
+}
+
+impl ProductFacts {
+    pub fn from_off_response(value: &Value) -> Self {
+        let product = &value["product"];
+        let nutriments = &product["nutriments"];
+        Self {
+            product_name: product["product_name"].as_str().map(String::from),
+            selected_image: product["selected_images"]["front"]["display"]["en"]
+                .as_str()
+                .map(String::from),
+            serving_size: product["serving_size"].as_str().map(String::from),
+            calories_kcal: nutrient(nutriments, "energy-kcal"),
+            carbohydrates_g: nutrient(nutriments, "carbohydrates"),
+            protein_g: nutrient(nutriments, "proteins"),
+            fat_g: nutrient(nutriments, "fat"),
+            nutriscore_grade: product["nutriscore_grade"].as_str().map(String::from),
+            nova_group: product["nova_group"]
+                .as_u64()
+                .map(|group| group.to_string())
+                .or_else(|| product["nova_group"].as_str().map(String::from)),
+            allergens: product["allergens_tags"]
+                .as_array()
+                .map(|tags| {
+                    tags.iter()
+                        .filter_map(|tag| tag.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Prefers the `{key}_serving` nutrient value, falling back to
+/// `{key}_100g` when OFF hasn't recorded a serving-based figure (the
+/// frequent case that used to render as `null`), and tags which basis won.
+fn nutrient(nutriments: &Value, key: &str) -> Option<Nutrient> {
+    if let Some(value) = nutriments[format!("{key}_serving")].as_f64() {
+        return Some(Nutrient {
+            value,
+            basis: NutrientBasis::PerServing,
+        });
+    }
+    nutriments[format!("{key}_100g")]
+        .as_f64()
+        .map(|value| Nutrient {
+            value,
+            basis: NutrientBasis::Per100g,
+        })
+}
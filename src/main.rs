@@ -1,42 +1,148 @@
+mod decode;
+mod facts;
+mod state;
+
 use anyhow::{Context, Result};
 use axum::{
-    extract::{DefaultBodyLimit, Multipart},
-    response::Html,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
-    Router,
+    Json, Router,
 };
+use facts::ProductFacts;
 use log::{error, info};
 use openfoodfacts as off;
 use serde_json::{json, Value};
-use std::sync::Arc;
-use std::{collections::HashMap, fs};
-use tokio::io::AsyncWriteExt;
+use state::AppState;
+use std::{collections::HashMap, time::Duration};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeFile;
 
-fn create_nutrional_facts_file(file_name: &str) -> Result<String> {
-    let client = off::v2().build()?;
-    let bar_code = rxing::helpers::detect_in_file(file_name, None)?;
-    let bar_code_text = bar_code.getText();
-    let code = bar_code_text;
-    let response = client.product(code, None).unwrap();
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+fn fetch_product_facts(state: &AppState, code: &str) -> Result<ProductFacts> {
+    if let Some(facts) = state.get(code) {
+        return Ok(facts);
+    }
+
+    let response = state
+        .client
+        .product(code, None)
+        .context("OFF product lookup failed")?;
     let result_json = json!(response.json::<HashMap::<String, Value>>()?);
-    fs::write("res.json", &result_json.to_string())?;
-    let selected_image = &result_json["product"]["selected_images"]["front"]["display"]["en"];
-    let serving_size = &result_json["product"]["serving_size"];
-    let calories_per = &result_json["product"]["nutriments"]["energy-kcal_serving"];
-    let carbs_per = &result_json["product"]["nutriments"]["carbohydrates_serving"];
-    let protein_per = &result_json["product"]["nutriments"]["proteins_serving"];
-    let fats_per = &result_json["product"]["nutriments"]["fat_serving"];
-    Ok(format!(
-        "<img src={selected_image} width=25% height=auto>
-         <h1><b>Tamaño de serving</b>: {serving_size}<br>
-    <b>Valores nutricionales (por serving)</b>:<br>
-    <b>Calorías (kcal)</b>: {calories_per}<br>
-    <b>Carbohidratos</b>: {carbs_per}g<br>
-    <b>Proteína</b>: {protein_per}<br>
-    <b>Grasa</b>: {fats_per}g</h1>"
-    ))
+    let facts = ProductFacts::from_off_response(&result_json);
+    state.insert(code.to_string(), facts.clone());
+    Ok(facts)
+}
+
+/// Escapes the characters that matter in both HTML text content and
+/// quoted attribute values. OFF is crowd-edited, so any free-text field
+/// (product name, Nutri-Score grade, allergen tags, image URL) has to be
+/// treated as untrusted before it's spliced into the card markup.
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_nutrient(label: &str, nutrient: &Option<facts::Nutrient>, unit: &str) -> String {
+    match nutrient {
+        Some(n) => format!(
+            "<b>{label} ({})</b>: {}{unit}<br>",
+            n.basis.badge(),
+            n.value
+        ),
+        None => format!("<b>{label}</b>: N/A<br>"),
+    }
+}
+
+fn render_card(facts: &ProductFacts) -> String {
+    let product_name = facts
+        .product_name
+        .as_deref()
+        .map(escape_html)
+        .unwrap_or_else(|| "Producto".to_string());
+    let selected_image = facts
+        .selected_image
+        .as_deref()
+        .map(escape_html)
+        .unwrap_or_default();
+    let serving_size = facts
+        .serving_size
+        .as_deref()
+        .map(escape_html)
+        .unwrap_or_default();
+    let calories = render_nutrient("Calorías (kcal)", &facts.calories_kcal, "");
+    let carbs = render_nutrient("Carbohidratos", &facts.carbohydrates_g, "g");
+    let protein = render_nutrient("Proteína", &facts.protein_g, "g");
+    let fats = render_nutrient("Grasa", &facts.fat_g, "g");
+    let nutriscore = facts
+        .nutriscore_grade
+        .as_deref()
+        .map(|grade| format!("<b>Nutri-Score</b>: {}<br>", escape_html(&grade.to_uppercase())))
+        .unwrap_or_default();
+    let allergens = if facts.allergens.is_empty() {
+        String::new()
+    } else {
+        let escaped: Vec<String> = facts.allergens.iter().map(|a| escape_html(a)).collect();
+        format!("<b>Alérgenos</b>: {}<br>", escaped.join(", "))
+    };
+    format!(
+        "<img src=\"{selected_image}\" width=25% height=auto>
+         <h1><b>{product_name}</b><br>
+    <b>Tamaño de serving</b>: {serving_size}<br>
+    {calories}
+    {carbs}
+    {protein}
+    {fats}
+    {nutriscore}
+    {allergens}</h1>"
+    )
+}
+
+/// Decodes an uploaded image and fetches `ProductFacts` for every barcode
+/// found. Shared by the HTMX `/upload` route and the JSON `/api/upload`
+/// route so there is exactly one place that turns bytes into facts; only
+/// the rendering differs per route.
+async fn decode_and_fetch(state: AppState, file_data: Vec<u8>) -> Result<Vec<ProductFacts>, ()> {
+    tokio::task::spawn_blocking(move || {
+        let codes = match decode::try_decode(&file_data) {
+            Ok(codes) => codes,
+            Err(_) => {
+                error!("Could not read the image");
+                return Err(());
+            }
+        };
+
+        Ok(codes
+            .iter()
+            .filter_map(|code| {
+                fetch_product_facts(&state, code)
+                    .map_err(|e| error!("Could not fetch product {code}: {e}"))
+                    .ok()
+            })
+            .collect())
+    })
+    .await
+    .unwrap()
+}
+
+/// Reads the single image field out of a multipart upload.
+async fn read_uploaded_image(multipart: &mut Multipart) -> Result<Vec<u8>, &'static str> {
+    let mut file_data = Vec::new();
+    while let Some(field) = multipart.next_field().await.unwrap() {
+        let content_type = field.content_type().unwrap().to_string();
+        let data = field.bytes().await.unwrap();
+        if !content_type.starts_with("image/") {
+            error!("The uploader did not sent an image");
+            return Err("Please upload only images.");
+        }
+        file_data = data.to_vec();
+    }
+    Ok(file_data)
 }
 
 #[tokio::main]
@@ -48,14 +154,18 @@ async fn main() -> Result<()> {
         .allow_origin(Any)
         .allow_headers(Any)
         .allow_methods(Any);
+    let state = AppState::new(off::v2().build()?, CACHE_TTL);
     let app = Router::new()
         .route("/", get(root))
         .route("/sanity", get(sanity_check))
         // Set the upload limit to 10mb (this will be loaded into memory)
         .route("/upload", post(upload))
+        .route("/api/upload", post(api_upload))
+        .route("/api/product/:barcode", get(api_product))
         .route_service("/pepe", ServeFile::new("pepe.png"))
         .layer(DefaultBodyLimit::max(100 * 100 * 1000))
-        .layer(cors);
+        .layer(cors)
+        .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
     println!("Listening on: http://{}", listener.local_addr()?);
@@ -114,44 +224,80 @@ async fn sanity_check() -> &'static str {
     "Server is up and runnning!\n"
 }
 
-async fn upload(mut multipart: Multipart) -> Html<String> {
+/// Whether the client asked for `application/json`, so `/upload` can serve
+/// either the Spanish HTML fragment or the JSON API response from the same
+/// route depending on the `Accept` header.
+fn wants_json(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"))
+}
+
+async fn upload(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
     info!("Got upload request");
-    let mut file_name = String::new();
-    let mut file_data = Vec::new();
+    let file_data = match read_uploaded_image(&mut multipart).await {
+        Ok(file_data) => file_data,
+        Err(message) => return Html(format!("<p>{message}</p>")).into_response(),
+    };
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let fname = field.file_name().unwrap().to_string();
-        let content_type = field.content_type().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-        if !content_type.starts_with("image/") {
-            error!("The uploader did not sent an image");
-            return Html("<p>Please upload only images.</p>".to_string());
+    match decode_and_fetch(state, file_data).await {
+        Ok(facts) if wants_json(&headers) => Json(facts).into_response(),
+        Ok(facts) if facts.is_empty() => {
+            Html("<p>Código de barras leído, pero el producto no está en OpenFoodFacts.</p>".to_string())
+                .into_response()
+        }
+        Ok(facts) => Html(
+            facts
+                .iter()
+                .map(render_card)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+        .into_response(),
+        Err(()) if wants_json(&headers) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "could not read file, make sure it is a valid image!" })),
+        )
+            .into_response(),
+        Err(()) => Html("could not read file, make sure it is a valid image!".to_string())
+            .into_response(),
+    }
+}
+
+async fn api_upload(State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    info!("Got API upload request");
+    let file_data = match read_uploaded_image(&mut multipart).await {
+        Ok(file_data) => file_data,
+        Err(message) => {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
         }
+    };
 
-        file_name = fname;
-        file_data = data.to_vec();
+    match decode_and_fetch(state, file_data).await {
+        Ok(facts) => Json(facts).into_response(),
+        Err(()) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "error": "could not read file, make sure it is a valid image!" })),
+        )
+            .into_response(),
+    }
+}
+
+async fn api_product(State(state): State<AppState>, Path(barcode): Path<String>) -> Response {
+    info!("Got API product request for {barcode}");
+    let facts =
+        tokio::task::spawn_blocking(move || fetch_product_facts(&state, &barcode)).await;
+    match facts {
+        Ok(Ok(facts)) => Json(facts).into_response(),
+        _ => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "product not found" })),
+        )
+            .into_response(),
     }
-    let file_name_with_extension = Arc::new(String::from(file_name));
-    let file_name_with_extension_clone = file_name_with_extension.clone();
-    let file_name_with_extension_clone_2 = file_name_with_extension.clone();
-    let mut file = tokio::fs::File::create(file_name_with_extension.as_str())
-        .await
-        .unwrap();
-    file.write_all(&file_data)
-        .await
-        .with_context(|| format!("Failed to create file"))
-        .unwrap();
-    let response = tokio::task::spawn_blocking(move || {
-        let file_name = file_name_with_extension_clone.as_str();
-        create_nutrional_facts_file(file_name).unwrap_or_else(|_| {
-            error!("Could not read the image");
-            String::from("could not read file, make sure it is a valid image!")
-        })
-    })
-    .await
-    .unwrap();
-    tokio::fs::remove_file(file_name_with_extension_clone_2.as_str())
-        .await
-        .unwrap();
-    Html(response)
 }
@@ -0,0 +1,62 @@
+use crate::facts::ProductFacts;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use openfoodfacts as off;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A cached `ProductFacts` alongside when it was fetched, so entries can be
+/// expired after `AppState::cache_ttl`.
+struct CacheEntry {
+    facts: ProductFacts,
+    inserted_at: DateTime<Utc>,
+}
+
+/// Shared state injected into every handler via axum's `State` extractor:
+/// a barcode -> nutrition cache and the single OpenFoodFacts client, so we
+/// stop paying for a client build and a network round-trip on every scan
+/// of a product we've already seen.
+#[derive(Clone)]
+pub struct AppState {
+    cache: Arc<DashMap<String, CacheEntry>>,
+    pub client: Arc<off::Client>,
+    cache_ttl: Duration,
+}
+
+impl AppState {
+    pub fn new(client: off::Client, cache_ttl: Duration) -> Self {
+        Self {
+            cache: Arc::new(DashMap::new()),
+            client: Arc::new(client),
+            cache_ttl,
+        }
+    }
+
+    /// Returns the cached facts for `code`, evicting them first if the TTL
+    /// has elapsed.
+    pub fn get(&self, code: &str) -> Option<ProductFacts> {
+        let expired = self
+            .cache
+            .get(code)
+            .map(|entry| Utc::now().signed_duration_since(entry.inserted_at) > self.ttl())?;
+        if expired {
+            self.cache.remove(code);
+            return None;
+        }
+        self.cache.get(code).map(|entry| entry.facts.clone())
+    }
+
+    pub fn insert(&self, code: String, facts: ProductFacts) {
+        self.cache.insert(
+            code,
+            CacheEntry {
+                facts,
+                inserted_at: Utc::now(),
+            },
+        );
+    }
+
+    fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::from_std(self.cache_ttl).unwrap_or(chrono::Duration::zero())
+    }
+}